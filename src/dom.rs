@@ -1,5 +1,14 @@
+use crate::encoding::{self, Encoding};
+use crate::parser::ParseOpts;
+use crate::tokenizer::Attribute;
+use crate::tree_sink::TreeSink;
+
 pub enum NodeType {
-    DocumentNode
+    DocumentNode,
+    DoctypeNode,
+    ElementNode,
+    TextNode,
+    CommentNode,
 }
 
 #[allow(dead_code)]
@@ -12,7 +21,7 @@ pub trait Node {
     fn parent_node(&self) -> Option<impl Node>;
     fn first_child(&self) -> Option<impl Node>;
     fn next_sibling(&self) -> Option<impl Node>;
-    fn append_child<T>(&self, child: _Node<T>);
+    fn append_child(&self, child: DomNode);
 }
 
 #[allow(dead_code)]
@@ -22,36 +31,119 @@ pub trait Document : Node {
 
 pub type _Node<T> = rctree::Node<T>;
 
+/// A parsed `<!DOCTYPE ...>` declaration.
+pub struct DocumentType {
+    pub name: String,
+    pub public_id: String,
+    pub system_id: String,
+}
+
+/// Which rendering mode a document should be treated in, per the HTML
+/// standard's "quirks mode" determination from the DOCTYPE.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuirksMode {
+    NoQuirks,
+    LimitedQuirks,
+    Quirks,
+}
+
 pub struct DocumentData {
     url: String,
+    pub doctype: Option<DocumentType>,
+    pub quirks_mode: QuirksMode,
+}
+
+pub struct ElementData {
+    pub name: String,
+    pub attributes: Vec<Attribute>,
+}
+
+pub struct TextData(pub String);
+
+pub struct CommentData(pub String);
+
+/// Every kind of node that can appear in the tree, following the RcDom
+/// layout: one node type shared across the whole document, tagged by what
+/// it actually holds.
+pub enum NodeData {
+    Document(DocumentData),
+    Doctype(DocumentType),
+    Element(ElementData),
+    Text(TextData),
+    Comment(CommentData),
 }
-pub type DocumentNode = _Node<DocumentData>;
 
-impl Document for DocumentNode {
-    fn base_url<'a>(&'a self) -> String {
-        self.borrow().url.clone()
+/// A node in the tree. Every node, regardless of kind, is this type; what it
+/// represents is determined by its `NodeData`.
+pub type DomNode = _Node<NodeData>;
+
+// Aliases for readability at call sites that only ever hold one kind of node.
+pub type DocumentNode = DomNode;
+pub type DoctypeNode = DomNode;
+pub type ElementNode = DomNode;
+pub type TextNode = DomNode;
+pub type CommentNode = DomNode;
+
+/// Walk up to the root of `node`'s tree. A free function rather than an
+/// inherent method on `DomNode`, since `DomNode` is a type alias for
+/// `rctree::Node<NodeData>` -- a type this crate doesn't own, so Rust's
+/// orphan rule forbids an inherent `impl` block for it here.
+fn root(node: &DomNode) -> DomNode {
+    let mut current = node.clone();
+    while let Some(parent) = current.parent() {
+        current = parent;
+    }
+    current
+}
+
+impl Document for DomNode {
+    fn base_url(&self) -> String {
+        match &*self.borrow() {
+            NodeData::Document(data) => data.url.clone(),
+            _ => String::new(),
+        }
     }
 }
 
-impl Node for DocumentNode {
+impl Node for DomNode {
     fn node_type(&self) -> NodeType {
-        NodeType::DocumentNode
+        match &*self.borrow() {
+            NodeData::Document(_) => NodeType::DocumentNode,
+            NodeData::Doctype(_) => NodeType::DoctypeNode,
+            NodeData::Element(_) => NodeType::ElementNode,
+            NodeData::Text(_) => NodeType::TextNode,
+            NodeData::Comment(_) => NodeType::CommentNode,
+        }
     }
 
     fn node_name(&self) -> String {
-        String::from("#document")
+        match &*self.borrow() {
+            NodeData::Document(_) => String::from("#document"),
+            NodeData::Doctype(doctype) => doctype.name.clone(),
+            NodeData::Element(element) => element.name.to_ascii_uppercase(),
+            NodeData::Text(_) => String::from("#text"),
+            NodeData::Comment(_) => String::from("#comment"),
+        }
     }
 
     fn owner_document(&self) -> Option<impl Document> {
-        Some(self.clone())
+        let root = root(self);
+        let is_document = matches!(&*root.borrow(), NodeData::Document(_));
+        if is_document {
+            Some(root)
+        } else {
+            None
+        }
     }
 
     fn base_uri(&self) -> String {
-        self.base_url()
+        self.owner_document()
+            .map(|document| document.base_url())
+            .unwrap_or_default()
     }
 
     fn is_connected(&self) -> bool {
-        true
+        self.owner_document().is_some()
     }
 
     fn parent_node(&self) -> Option<impl Node> {
@@ -59,14 +151,279 @@ impl Node for DocumentNode {
     }
 
     fn first_child(&self) -> Option<impl Node> {
-        self.first_child()    
+        self.first_child()
     }
 
     fn next_sibling(&self) -> Option<impl Node> {
-        self.next_sibling()    
+        self.next_sibling()
     }
 
-    fn append_child<T>(&self, child: _Node<T>) {
+    fn append_child(&self, child: DomNode) {
         self.append(child);
     }
 }
+
+/// The default `TreeSink`: builds the `rctree`-based node graph above.
+///
+/// This reproduces the parser's previous hard-coded behavior of building a
+/// single `Document`, just expressed through the `TreeSink` interface so
+/// callers who don't need a custom tree representation can use it as-is.
+pub struct RcTreeSink {
+    document: DocumentNode,
+}
+
+impl Default for RcTreeSink {
+    fn default() -> RcTreeSink {
+        RcTreeSink::new()
+    }
+}
+
+impl RcTreeSink {
+    pub fn new() -> RcTreeSink {
+        RcTreeSink {
+            document: _Node::new(NodeData::Document(DocumentData {
+                url: String::new(),
+                doctype: None,
+                quirks_mode: QuirksMode::NoQuirks,
+            })),
+        }
+    }
+
+    pub fn document(self) -> DocumentNode {
+        self.document
+    }
+}
+
+impl TreeSink for RcTreeSink {
+    type Handle = DomNode;
+
+    fn parse_error(&mut self, _message: String) {
+        // Diagnostics already flow through the collected `errors` vec
+        // (`ParseOutput::errors`, surfaced on `Dom::errors`); nothing to do here.
+    }
+
+    fn get_document(&self) -> Self::Handle {
+        self.document.clone()
+    }
+
+    fn get_template_contents(&mut self, target: &Self::Handle) -> Self::Handle {
+        target.clone()
+    }
+
+    fn create_element(&mut self, name: String, mut attributes: Vec<Attribute>) -> Self::Handle {
+        let base_url = match &*self.document.borrow() {
+            NodeData::Document(data) => data.url.clone(),
+            _ => String::new(),
+        };
+        if !base_url.is_empty() {
+            for attribute in attributes.iter_mut() {
+                if attribute.name == "href" || attribute.name == "src" {
+                    attribute.value = resolve_url(&base_url, &attribute.value);
+                }
+            }
+        }
+        _Node::new(NodeData::Element(ElementData { name, attributes }))
+    }
+
+    fn create_comment(&mut self, text: String) -> Self::Handle {
+        _Node::new(NodeData::Comment(CommentData(text)))
+    }
+
+    fn create_text(&mut self, text: String) -> Self::Handle {
+        _Node::new(NodeData::Text(TextData(text)))
+    }
+
+    fn append(&mut self, parent: &Self::Handle, child: Self::Handle) {
+        parent.append(child);
+    }
+
+    fn append_doctype_to_document(&mut self, name: String, public_id: String, system_id: String) {
+        if let NodeData::Document(ref mut data) = *self.document.borrow_mut() {
+            data.doctype = Some(DocumentType {
+                name,
+                public_id,
+                system_id,
+            });
+        }
+    }
+
+    fn set_quirks_mode(&mut self, mode: QuirksMode) {
+        if let NodeData::Document(ref mut data) = *self.document.borrow_mut() {
+            data.quirks_mode = mode;
+        }
+    }
+
+    fn set_document_base_url(&mut self, url: String) {
+        if let NodeData::Document(ref mut data) = *self.document.borrow_mut() {
+            data.url = url;
+        }
+    }
+}
+
+/// A parsed document, returned by `Dom::parse`.
+pub struct Dom {
+    pub document: DocumentNode,
+    /// The character encoding the input was decoded from. Always
+    /// `Encoding::Utf8` for `Dom::parse`, since a Rust `&str` is already
+    /// decoded; `Dom::parse_bytes` resolves this from a BOM or a declared
+    /// `<meta charset>` before decoding.
+    pub encoding: Encoding,
+    /// Parse errors recovered along the way -- ordinary tag soup (an
+    /// unclosed `<p>`, a misnested `<b>`/`<i>` pair, ...) still produces a
+    /// `Dom`, with the errors the parser recovered from collected here
+    /// rather than aborting the parse.
+    pub errors: Vec<ParseError>,
+}
+
+/// A parse error recovered while building a `Dom`: what went wrong, and
+/// where in the input.
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub offset: usize,
+    pub reason: String,
+}
+
+impl Dom {
+    /// Parse `html` into a `Dom`. HTML parsing is defined to always recover
+    /// and produce a tree -- `Ok` carries that tree along with whatever
+    /// errors were recovered along the way, on `Dom::errors`. `Err` is
+    /// reserved for failures that aren't recoverable parse errors; none of
+    /// the `Dom` constructors returns it, and `parse_reader` treats a read
+    /// error from its source as the end of the input.
+    pub fn parse(html: &str) -> Result<Dom, ParseError> {
+        Dom::parse_with_base_url(html, "")
+    }
+
+    /// Like `Dom::parse`, but resolves the document against `base_url`:
+    /// relative `href`/`src` attributes are rewritten to absolute URLs as
+    /// the tree is built, and `owner_document().base_url()`/`base_uri()`
+    /// return `base_url` instead of the empty string.
+    pub fn parse_with_base_url(html: &str, base_url: &str) -> Result<Dom, ParseError> {
+        let output = crate::parser::parse(
+            html,
+            RcTreeSink::new(),
+            base_url,
+            ParseOpts { exact_errors: true },
+        );
+        Ok(Dom::from_parse_output(output))
+    }
+
+    /// Shared by every `Dom` constructor: wraps up the parsed document with
+    /// its recovered errors and its encoding defaulted to UTF-8 (callers
+    /// that resolved a different encoding, like `parse_bytes`, override it
+    /// afterward).
+    fn from_parse_output(output: crate::parser::ParseOutput<DocumentNode>) -> Dom {
+        let errors = output
+            .errors
+            .into_iter()
+            .map(|error| ParseError { offset: error.offset, reason: error.message })
+            .collect();
+        Dom { document: output.document, encoding: Encoding::Utf8, errors }
+    }
+
+    /// Parse `reader` into a `Dom` incrementally, without buffering the
+    /// whole document in memory first -- useful for files too large to read
+    /// up front. Built on `Tokenizer::from_reader`, which pulls and decodes
+    /// input in small batches as the tokenizer runs low, and holds its state
+    /// (in-tag, in-attribute-value, in-comment, ...) across those batches the
+    /// same way it does across `feed` calls.
+    pub fn parse_reader<R: std::io::Read + 'static>(reader: R) -> Result<Dom, ParseError> {
+        Dom::parse_reader_with_base_url(reader, "")
+    }
+
+    /// Like `Dom::parse_reader`, but resolves the document against
+    /// `base_url` -- see `Dom::parse_with_base_url`.
+    pub fn parse_reader_with_base_url<R: std::io::Read + 'static>(
+        reader: R,
+        base_url: &str,
+    ) -> Result<Dom, ParseError> {
+        let mut sink = RcTreeSink::new();
+        sink.set_document_base_url(base_url.to_string());
+        let tokenizer = crate::tokenizer::Tokenizer::from_reader(
+            std::io::BufReader::new(reader),
+            crate::tokenizer::DefaultEmitter::new(),
+            crate::tokenizer::TokenizerOpts { exact_errors: true, ..Default::default() },
+        );
+        let output = crate::parser::parse_tokenizer(tokenizer, sink, ParseOpts { exact_errors: true });
+        Ok(Dom::from_parse_output(output))
+    }
+
+    /// Parse a raw byte stream into a `Dom`, resolving its character
+    /// encoding first: a leading BOM takes priority, then a declared
+    /// `<meta charset>`/`<meta http-equiv="Content-Type">`, defaulting to
+    /// UTF-8 if neither is present. The resolved encoding is available
+    /// afterward on `Dom::encoding`.
+    pub fn parse_bytes(bytes: &[u8]) -> Result<Dom, ParseError> {
+        Dom::parse_bytes_with_base_url(bytes, "")
+    }
+
+    /// Like `Dom::parse_bytes`, but resolves the document against
+    /// `base_url` -- see `Dom::parse_with_base_url`.
+    pub fn parse_bytes_with_base_url(bytes: &[u8], base_url: &str) -> Result<Dom, ParseError> {
+        let resolved = encoding::detect_encoding(bytes);
+        let html = encoding::decode(bytes, &resolved);
+        Dom::parse_with_base_url(&html, base_url).map(|dom| Dom { encoding: resolved, ..dom })
+    }
+
+    /// Serialize this document back into well-formed HTML -- the inverse of
+    /// `parse`. Thin wrapper around `serializer::serialize` for symmetry
+    /// with `Dom::parse`.
+    pub fn to_html(&self) -> String {
+        crate::serializer::serialize(&self.document, crate::serializer::SerializeOpts::default())
+    }
+}
+
+/// Resolve `relative` against `base`, following the usual URL join/normalize
+/// semantics. Falls back to the relative string unchanged if either one
+/// doesn't parse as a URL.
+fn resolve_url(base: &str, relative: &str) -> String {
+    url::Url::parse(base)
+        .and_then(|base| base.join(relative))
+        .map(|resolved| resolved.to_string())
+        .unwrap_or_else(|_| relative.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ordinary_tag_soup_still_parses_to_a_tree_instead_of_erroring() {
+        // Regression test: Dom::parse used to reject on the first recovered
+        // error, throwing away the tree for ordinary mis-nested markup.
+        let dom = Dom::parse("<p>hi</p></div>").expect("tag soup should still parse");
+        assert!(!dom.errors.is_empty());
+
+        let dom = Dom::parse("<b>bold</i>").expect("mismatched end tag should still parse");
+        assert!(!dom.errors.is_empty());
+    }
+
+    #[test]
+    fn parse_with_base_url_resolves_relative_href_and_exposes_base_uri() {
+        let dom = Dom::parse_with_base_url(
+            r#"<a href="/page">link</a>"#,
+            "https://example.com/dir/",
+        ).expect("should parse");
+
+        assert_eq!(dom.document.base_url(), "https://example.com/dir/");
+
+        let href = find_href(&dom.document).expect("expected an <a href> in the tree");
+        assert_eq!(href, "https://example.com/page");
+    }
+
+    fn find_href(node: &DomNode) -> Option<String> {
+        if let NodeData::Element(element) = &*node.borrow() {
+            if let Some(attribute) = element.attributes.iter().find(|attribute| attribute.name == "href") {
+                return Some(attribute.value.clone());
+            }
+        }
+        let mut child = node.first_child();
+        while let Some(current) = child {
+            if let Some(found) = find_href(&current) {
+                return Some(found);
+            }
+            child = current.next_sibling();
+        }
+        None
+    }
+}
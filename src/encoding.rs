@@ -0,0 +1,282 @@
+/// How many leading bytes of the document are scanned for a BOM or a
+/// declared charset, mirroring the practical sniffing window browsers use
+/// rather than scanning the whole (possibly huge) document.
+const SNIFF_WINDOW: usize = 1024;
+
+/// The character encoding a document was decoded from, resolved by
+/// `detect_encoding` and exposed on `Dom` so callers know what was used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// ISO-8859-1 (Latin-1): every byte maps directly to the Unicode code
+    /// point of the same value.
+    Iso8859_1,
+    /// windows-1252: ISO-8859-1 except that 27 of the 32 bytes in 0x80-0x9F
+    /// map to specific Unicode code points instead of the C1 controls.
+    Windows1252,
+    /// A label was found but this crate doesn't
+    /// implement a decoder for it; the bytes are decoded as UTF-8 with
+    /// replacement characters standing in for whatever doesn't fit, rather
+    /// than silently mis-decoding them.
+    Unsupported(String),
+}
+
+impl Encoding {
+    /// The canonical label for this encoding, as you'd see in a
+    /// `charset=` declaration.
+    pub fn label(&self) -> &str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Iso8859_1 => "ISO-8859-1",
+            Encoding::Windows1252 => "windows-1252",
+            Encoding::Unsupported(label) => label,
+        }
+    }
+
+    /// Resolve the charset label text (trimmed, already lowercased) found
+    /// in a BOM-less document to a concrete `Encoding`.
+    fn from_label(label: &str) -> Encoding {
+        match label {
+            "utf-8" | "utf8" => Encoding::Utf8,
+            "iso-8859-1" | "latin1" | "latin-1" | "l1" => Encoding::Iso8859_1,
+            "windows-1252" | "cp1252" | "x-cp1252" | "ansi_x3.4-1968" | "ascii" | "us-ascii" => {
+                Encoding::Windows1252
+            }
+            other => Encoding::Unsupported(other.to_string()),
+        }
+    }
+}
+
+/// Detect the character encoding of `bytes`: a leading BOM takes priority,
+/// then a `<meta charset=...>`/`<meta http-equiv="Content-Type" ...>`
+/// declaration within the first `SNIFF_WINDOW` bytes, defaulting to UTF-8 if
+/// neither is present.
+pub fn detect_encoding(bytes: &[u8]) -> Encoding {
+    if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        return Encoding::Utf8;
+    }
+    if bytes.starts_with(&[0xFF, 0xFE]) {
+        return Encoding::Utf16Le;
+    }
+    if bytes.starts_with(&[0xFE, 0xFF]) {
+        return Encoding::Utf16Be;
+    }
+
+    let window = &bytes[..bytes.len().min(SNIFF_WINDOW)];
+    // Meta declarations are always ASCII; any non-ASCII byte is replaced
+    // rather than decoded, since we don't know the encoding yet -- that's
+    // exactly what we're trying to find out.
+    let sniffable: String = window
+        .iter()
+        .map(|&b| if b.is_ascii() { b as char } else { '\u{fffd}' })
+        .collect();
+
+    match meta_charset(&sniffable) {
+        Some(label) => Encoding::from_label(&label),
+        None => Encoding::Utf8,
+    }
+}
+
+/// Scan `text` for the charset declared by a `<meta>` tag, per the two forms
+/// the HTML standard recognizes: a `charset` attribute directly, or a
+/// `http-equiv="Content-Type"` tag whose `content` attribute embeds one.
+fn meta_charset(text: &str) -> Option<String> {
+    let lower = text.to_ascii_lowercase();
+    let mut search_from = 0;
+
+    while let Some(start) = lower[search_from..].find("<meta") {
+        let tag_start = search_from + start;
+        let tag_end = lower[tag_start..].find('>').map(|i| tag_start + i);
+        let Some(tag_end) = tag_end else { break };
+        let tag = &lower[tag_start..tag_end];
+
+        if let Some(charset) = attribute_value(tag, "charset") {
+            return Some(trim_charset(&charset));
+        }
+        if tag.contains("http-equiv") && tag.contains("content-type") {
+            if let Some(content) = attribute_value(tag, "content") {
+                if let Some(at) = content.find("charset=") {
+                    return Some(trim_charset(&content[at + "charset=".len()..]));
+                }
+            }
+        }
+
+        search_from = tag_end + 1;
+    }
+
+    None
+}
+
+/// Find `name="value"`/`name='value'`/`name=value` within `tag` (already
+/// lowercased) and return `value`, unquoted but not yet trimmed.
+fn attribute_value(tag: &str, name: &str) -> Option<String> {
+    let needle = format!("{}=", name);
+    let at = tag.find(&needle)? + needle.len();
+    let rest = &tag[at..];
+    match rest.as_bytes().first() {
+        Some(b'"') => rest[1..].find('"').map(|end| rest[1..1 + end].to_string()),
+        Some(b'\'') => rest[1..].find('\'').map(|end| rest[1..1 + end].to_string()),
+        _ => {
+            let end = rest.find(|ch: char| ch.is_whitespace() || ch == '>').unwrap_or(rest.len());
+            Some(rest[..end].to_string())
+        }
+    }
+}
+
+/// Trim the quotes/whitespace/trailing-`;` noise that can surround a
+/// `charset=` value, e.g. in `content="text/html; charset=utf-8"`.
+fn trim_charset(value: &str) -> String {
+    value
+        .trim()
+        .trim_matches(|ch| ch == '"' || ch == '\'')
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_string()
+}
+
+/// Decode `bytes` as `encoding`, producing the `String` the tokenizer
+/// actually runs on.
+pub fn decode(bytes: &[u8], encoding: &Encoding) -> String {
+    match encoding {
+        Encoding::Utf8 => String::from_utf8_lossy(strip_utf8_bom(bytes)).into_owned(),
+        Encoding::Utf16Le => decode_utf16(bytes, u16::from_le_bytes),
+        Encoding::Utf16Be => decode_utf16(bytes, u16::from_be_bytes),
+        Encoding::Iso8859_1 => bytes.iter().map(|&b| b as char).collect(),
+        Encoding::Windows1252 => bytes.iter().map(|&b| windows_1252_char(b)).collect(),
+        Encoding::Unsupported(_) => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+fn strip_utf8_bom(bytes: &[u8]) -> &[u8] {
+    bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]).unwrap_or(bytes)
+}
+
+fn decode_utf16(bytes: &[u8], from_bytes: fn([u8; 2]) -> u16) -> String {
+    let bytes = bytes.strip_prefix(&[0xFF, 0xFE]).or_else(|| bytes.strip_prefix(&[0xFE, 0xFF])).unwrap_or(bytes);
+    let units = bytes.chunks_exact(2).map(|pair| from_bytes([pair[0], pair[1]]));
+    char::decode_utf16(units).map(|result| result.unwrap_or('\u{fffd}')).collect()
+}
+
+/// The 27 windows-1252 code points in 0x80-0x9F that differ from plain
+/// ISO-8859-1; everything else maps byte-for-byte.
+fn windows_1252_char(byte: u8) -> char {
+    match byte {
+        0x80 => '\u{20AC}',
+        0x82 => '\u{201A}',
+        0x83 => '\u{0192}',
+        0x84 => '\u{201E}',
+        0x85 => '\u{2026}',
+        0x86 => '\u{2020}',
+        0x87 => '\u{2021}',
+        0x88 => '\u{02C6}',
+        0x89 => '\u{2030}',
+        0x8A => '\u{0160}',
+        0x8B => '\u{2039}',
+        0x8C => '\u{0152}',
+        0x8E => '\u{017D}',
+        0x91 => '\u{2018}',
+        0x92 => '\u{2019}',
+        0x93 => '\u{201C}',
+        0x94 => '\u{201D}',
+        0x95 => '\u{2022}',
+        0x96 => '\u{2013}',
+        0x97 => '\u{2014}',
+        0x98 => '\u{02DC}',
+        0x99 => '\u{2122}',
+        0x9A => '\u{0161}',
+        0x9B => '\u{203A}',
+        0x9C => '\u{0153}',
+        0x9E => '\u{017E}',
+        0x9F => '\u{0178}',
+        // 0x81, 0x8D, 0x8F, 0x90, 0x9D are unassigned in windows-1252;
+        // fall back to the raw byte value like the rest of Latin-1.
+        other => other as char,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_utf8_bom() {
+        assert_eq!(detect_encoding(b"\xEF\xBB\xBF<html></html>"), Encoding::Utf8);
+    }
+
+    #[test]
+    fn detects_utf16_le_bom() {
+        assert_eq!(detect_encoding(b"\xFF\xFE<\x00"), Encoding::Utf16Le);
+    }
+
+    #[test]
+    fn detects_utf16_be_bom() {
+        assert_eq!(detect_encoding(b"\xFE\xFF\x00<"), Encoding::Utf16Be);
+    }
+
+    #[test]
+    fn defaults_to_utf8_with_no_bom_or_declared_charset() {
+        assert_eq!(detect_encoding(b"<html><body>hi</body></html>"), Encoding::Utf8);
+    }
+
+    #[test]
+    fn detects_charset_attribute_meta_tag() {
+        assert_eq!(
+            detect_encoding(b"<html><head><meta charset=\"windows-1252\"></head></html>"),
+            Encoding::Windows1252,
+        );
+    }
+
+    #[test]
+    fn detects_unquoted_charset_attribute() {
+        assert_eq!(
+            detect_encoding(b"<meta charset=iso-8859-1>"),
+            Encoding::Iso8859_1,
+        );
+    }
+
+    #[test]
+    fn detects_http_equiv_content_type_charset() {
+        assert_eq!(
+            detect_encoding(b"<meta http-equiv=\"Content-Type\" content=\"text/html; charset=utf-8\">"),
+            Encoding::Utf8,
+        );
+    }
+
+    #[test]
+    fn unrecognized_charset_label_is_unsupported() {
+        assert_eq!(
+            detect_encoding(b"<meta charset=\"shift_jis\">"),
+            Encoding::Unsupported("shift_jis".to_string()),
+        );
+    }
+
+    #[test]
+    fn bom_takes_priority_over_a_conflicting_meta_declaration() {
+        assert_eq!(
+            detect_encoding(b"\xEF\xBB\xBF<meta charset=\"windows-1252\">"),
+            Encoding::Utf8,
+        );
+    }
+
+    #[test]
+    fn decodes_windows_1252_c1_block() {
+        // 0x80 is the Euro sign in windows-1252, not U+0080.
+        assert_eq!(decode(&[0x80], &Encoding::Windows1252), "\u{20AC}");
+    }
+
+    #[test]
+    fn decodes_iso_8859_1_byte_for_byte() {
+        assert_eq!(decode(&[0xE9], &Encoding::Iso8859_1), "\u{e9}");
+    }
+
+    #[test]
+    fn decode_strips_utf8_bom() {
+        assert_eq!(decode(b"\xEF\xBB\xBFhi", &Encoding::Utf8), "hi");
+    }
+}
@@ -1,17 +1,6 @@
-use dom::Arena;
-use parser::parse;
-
-mod tokenizer;
-mod parser;
-mod dom;
+use html_parser::dom::Dom;
 
 fn main() {
-    let mut arena = Arena::new();
-    let mut html = arena.node("html");
-    let mut head = arena.node("head");
-    arena.append(&mut html, &mut head);
-
-    /*
     let input = "
         <!DOCTYPE html>
         <html>
@@ -26,8 +15,10 @@ fn main() {
                 </form>
             </body>
         </html>
-    " ;
+    ";
 
-    parse(input);
-    */
+    match Dom::parse(input) {
+        Ok(dom) => println!("{}", dom.to_html()),
+        Err(error) => eprintln!("parse error at offset {}: {}", error.offset, error.reason),
+    }
 }
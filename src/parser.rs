@@ -1,74 +1,695 @@
-use crate::tokenizer::{TagKind, Token, Tokenizer};
+use crate::dom::QuirksMode;
+use crate::tokenizer::{Doctype, TagKind, Token, Tokenizer, TokenizerOpts};
+use crate::tree_sink::TreeSink;
 
-pub fn parse(input: &str) {
-    let tokenizer = Tokenizer::new(input);
-
-    let mut doc = Document::new();
+/// Options controlling how `parse` behaves.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ParseOpts {
+    /// When set, parse errors carry a more detailed message. Otherwise only
+    /// the bare error code is reported.
+    pub exact_errors: bool,
+}
 
-    let mut state = ParserState::Initial;
-    let mut step = 1;
-    for token in tokenizer {
-        state = state.process_token(&mut doc, token);
-        println!("{}: {:?}", step, state);
-        step += 1;
-    }
+/// A single recovered parse error, identified by its spec error code.
+#[derive(Debug, Clone)]
+pub struct ParseError {
+    pub code: &'static str,
+    pub message: String,
+    /// Byte offset into the input where the error was found. Tree
+    /// construction doesn't track source positions yet, so errors raised
+    /// directly by `process_token` (as opposed to ones recovered while
+    /// tokenizing) report `0`.
+    pub offset: usize,
 }
 
+/// The result of a parse: the sink's document handle plus every parse error
+/// that was recovered from along the way.
 #[derive(Debug)]
-pub struct DocumentType {
-    pub name: String,
-    pub public_id: String,
-    pub system_id: String,
+pub struct ParseOutput<H> {
+    pub document: H,
+    pub errors: Vec<ParseError>,
 }
 
-#[derive(Debug)]
-pub struct Document {
-    pub doctype: Option<DocumentType>,
+pub fn parse<Sink: TreeSink>(
+    input: &str,
+    mut sink: Sink,
+    base_url: &str,
+    opts: ParseOpts,
+) -> ParseOutput<Sink::Handle> {
+    sink.set_document_base_url(base_url.to_string());
+
+    let tokenizer = Tokenizer::new_with_opts(
+        input,
+        TokenizerOpts { exact_errors: opts.exact_errors, ..TokenizerOpts::default() },
+    );
+
+    parse_tokenizer(tokenizer, sink, opts)
 }
 
-impl Document {
-    fn new() -> Document {
-        Document { doctype: None }
+/// Drive an already-built `Tokenizer` to completion against `sink`, the
+/// shared core of `parse` and `Dom::parse_reader`: the latter builds its
+/// tokenizer with `Tokenizer::from_reader` instead of feeding a whole `&str`
+/// up front, but tree construction proceeds identically either way.
+pub fn parse_tokenizer<Sink: TreeSink>(
+    mut tokenizer: Tokenizer<crate::tokenizer::DefaultEmitter>,
+    mut sink: Sink,
+    opts: ParseOpts,
+) -> ParseOutput<Sink::Handle> {
+    let mut state = ParserState::Initial;
+    let mut errors = Vec::new();
+    while let Some(token) = tokenizer.next() {
+        state = state.process_token(&mut sink, &mut errors, &opts, token);
+        for error in tokenizer.take_errors() {
+            errors.push(ParseError {
+                code: error.code,
+                message: error.message,
+                offset: error.offset,
+            });
+        }
+    }
+
+    ParseOutput {
+        document: sink.get_document(),
+        errors,
     }
 }
 
-#[derive(Debug)]
-enum ParserState {
+fn push_error<Sink: TreeSink>(
+    sink: &mut Sink,
+    errors: &mut Vec<ParseError>,
+    opts: &ParseOpts,
+    code: &'static str,
+) {
+    let message = if opts.exact_errors {
+        format!("parse error: {}", code)
+    } else {
+        code.to_string()
+    };
+    sink.parse_error(message.clone());
+    errors.push(ParseError { code, message, offset: 0 });
+}
+
+/// Tag names whose elements never have children and are never pushed onto
+/// the open-elements stack in `InBody`.
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param", "source",
+    "track", "wbr",
+];
+
+/// Head elements that don't change the insertion point: they're appended to
+/// `<head>` and left alone. Unlike `title`/`style`/`script` below, their
+/// content (if any) is ordinary markup rather than raw text, so there's no
+/// need to track an insertion point inside them.
+const HEAD_VOID_LIKE_ELEMENTS: &[&str] = &["base", "basefont", "bgsound", "link", "meta", "noscript", "noframes"];
+
+/// Head elements the tokenizer switches into RAWTEXT/RCDATA for (see the
+/// start-tag handling in `tokenizer.rs`): their content arrives as a run of
+/// `Char` tokens, with no distinction between whitespace and anything else,
+/// so -- unlike ordinary head elements -- it needs its own insertion point
+/// (`ParserState::Text`) rather than `InHead`'s whitespace-only `Char` arm.
+const HEAD_RAW_TEXT_ELEMENTS: &[&str] = &["title", "style", "script"];
+
+enum ParserState<H> {
     Initial,
     BeforeHtml,
+    BeforeHead { html: H },
+    InHead { html: H, head: H },
+    /// Mirrors the standard's "text" insertion mode: entered after a
+    /// `title`/`style`/`script` start tag inside `<head>`, so that every
+    /// `Char` token up to the matching end tag is appended to `element`
+    /// (the element just opened) rather than being routed through `InHead`
+    /// or treated as "anything else". Returns to `InHead` on the matching
+    /// end tag.
+    Text { html: H, head: H, element: H, name: String },
+    AfterHead { html: H },
+    /// `stack` is the chain of currently-open elements, closest ancestor
+    /// last; it always contains at least `<body>`. This is a minimal stand-in
+    /// for the HTML standard's full "stack of open elements" -- enough to
+    /// nest ordinary elements correctly, without the standard's per-element
+    /// special-case insertion rules (e.g. table/foster-parenting, implied
+    /// end tags).
+    InBody { html: H, stack: Vec<(H, String)> },
 }
 
-impl ParserState {
-    fn process_token(self, document: &mut Document, token: Token) -> ParserState {
+impl<H: Clone> ParserState<H> {
+    fn process_token<Sink: TreeSink<Handle = H>>(
+        self,
+        sink: &mut Sink,
+        errors: &mut Vec<ParseError>,
+        opts: &ParseOpts,
+        token: Token,
+    ) -> ParserState<H> {
         match self {
-            ParserState::BeforeHtml => {
-                match token {
-                    Token::Char('\t' | '\u{0a}' | '\u{0c}' | '\u{0d}' | ' ') => {
-                        ParserState::BeforeHtml
-                    }
-                    Token::Tag(tag) if matches!(tag.kind, TagKind::Start) && tag.name == "html" => {
-                        todo!()
-                    }
-                    _ => todo!(),
+            ParserState::Initial => match token {
+                Token::Char('\t' | '\u{0a}' | '\u{0c}' | '\u{0d}' | ' ') => ParserState::Initial,
+                Token::Doctype(doctype) => {
+                    sink.set_quirks_mode(quirks_mode_for_doctype(&doctype));
+                    sink.append_doctype_to_document(
+                        doctype.name,
+                        doctype.public_identifier.unwrap_or_default(),
+                        doctype.system_identifier.unwrap_or_default(),
+                    );
+                    ParserState::BeforeHtml
                 }
-            }
-            ParserState::Initial => {
+                _ => {
+                    sink.set_quirks_mode(QuirksMode::Quirks);
+                    ParserState::BeforeHtml.process_token(sink, errors, opts, token)
+                }
+            },
+            ParserState::BeforeHtml => match token {
+                Token::Char('\t' | '\u{0a}' | '\u{0c}' | '\u{0d}' | ' ') => ParserState::BeforeHtml,
+                Token::Tag(tag) if matches!(tag.kind, TagKind::Start) && tag.name == "html" => {
+                    let document = sink.get_document();
+                    let html = sink.create_element(tag.name, tag.attributes);
+                    sink.append(&document, html.clone());
+                    ParserState::BeforeHead { html }
+                }
+                Token::Doctype(_) => {
+                    push_error(sink, errors, opts, "unexpected-doctype");
+                    ParserState::BeforeHtml
+                }
+                Token::Tag(tag)
+                    if matches!(tag.kind, TagKind::End)
+                        && !matches!(tag.name.as_str(), "head" | "body" | "html" | "br") =>
+                {
+                    push_error(sink, errors, opts, "stray-end-tag");
+                    ParserState::BeforeHtml
+                }
+                Token::EOF => ParserState::BeforeHtml,
+                _ => {
+                    // "Anything else": act as if an `<html>` start tag had
+                    // been seen, then reprocess the token in `BeforeHead`.
+                    let document = sink.get_document();
+                    let html = sink.create_element("html".to_string(), Vec::new());
+                    sink.append(&document, html.clone());
+                    ParserState::BeforeHead { html }.process_token(sink, errors, opts, token)
+                }
+            },
+            ParserState::BeforeHead { html } => match token {
+                Token::Char('\t' | '\u{0a}' | '\u{0c}' | '\u{0d}' | ' ') => {
+                    ParserState::BeforeHead { html }
+                }
+                Token::Comment(text, _) => {
+                    let comment = sink.create_comment(text);
+                    sink.append(&html, comment);
+                    ParserState::BeforeHead { html }
+                }
+                Token::Doctype(_) => {
+                    push_error(sink, errors, opts, "unexpected-doctype");
+                    ParserState::BeforeHead { html }
+                }
+                Token::Tag(tag) if matches!(tag.kind, TagKind::Start) && tag.name == "head" => {
+                    let head = sink.create_element(tag.name, tag.attributes);
+                    sink.append(&html, head.clone());
+                    ParserState::InHead { html, head }
+                }
+                Token::Tag(tag)
+                    if matches!(tag.kind, TagKind::End)
+                        && !matches!(tag.name.as_str(), "head" | "body" | "html" | "br") =>
+                {
+                    push_error(sink, errors, opts, "stray-end-tag");
+                    ParserState::BeforeHead { html }
+                }
+                Token::EOF => ParserState::BeforeHead { html },
+                _ => {
+                    // "Anything else": act as if a `<head>` start tag had
+                    // been seen, then reprocess the token in `InHead`.
+                    let head = sink.create_element("head".to_string(), Vec::new());
+                    sink.append(&html, head.clone());
+                    ParserState::InHead { html, head }.process_token(sink, errors, opts, token)
+                }
+            },
+            ParserState::InHead { html, head } => match token {
+                Token::Char('\t' | '\u{0a}' | '\u{0c}' | '\u{0d}' | ' ') => {
+                    let text = sink.create_text(" ".to_string());
+                    sink.append(&head, text);
+                    ParserState::InHead { html, head }
+                }
+                Token::Comment(text, _) => {
+                    let comment = sink.create_comment(text);
+                    sink.append(&head, comment);
+                    ParserState::InHead { html, head }
+                }
+                Token::Doctype(_) => {
+                    push_error(sink, errors, opts, "unexpected-doctype");
+                    ParserState::InHead { html, head }
+                }
+                Token::Tag(tag) if matches!(tag.kind, TagKind::Start) && HEAD_VOID_LIKE_ELEMENTS.contains(&tag.name.as_str()) => {
+                    let element = sink.create_element(tag.name, tag.attributes);
+                    sink.append(&head, element);
+                    ParserState::InHead { html, head }
+                }
+                Token::Tag(tag)
+                    if matches!(tag.kind, TagKind::Start) && HEAD_RAW_TEXT_ELEMENTS.contains(&tag.name.as_str()) =>
+                {
+                    let name = tag.name.clone();
+                    let element = sink.create_element(tag.name, tag.attributes);
+                    sink.append(&head, element.clone());
+                    ParserState::Text { html, head, element, name }
+                }
+                Token::Tag(tag) if matches!(tag.kind, TagKind::End) && tag.name == "head" => {
+                    ParserState::AfterHead { html }
+                }
+                Token::Tag(tag)
+                    if matches!(tag.kind, TagKind::End)
+                        && !matches!(tag.name.as_str(), "body" | "html" | "br") =>
+                {
+                    push_error(sink, errors, opts, "stray-end-tag");
+                    ParserState::InHead { html, head }
+                }
+                Token::EOF => ParserState::InHead { html, head },
+                _ => {
+                    // "Anything else": act as if a `</head>` end tag had
+                    // been seen, then reprocess the token in `AfterHead`.
+                    ParserState::AfterHead { html }.process_token(sink, errors, opts, token)
+                }
+            },
+            ParserState::Text { html, head, element, name } => match token {
+                Token::Char(ch) => {
+                    let text = sink.create_text(ch.to_string());
+                    sink.append(&element, text);
+                    ParserState::Text { html, head, element, name }
+                }
+                Token::Tag(tag) if matches!(tag.kind, TagKind::End) && tag.name == name => {
+                    ParserState::InHead { html, head }
+                }
+                // Per the standard's "text" insertion mode, anything other
+                // than a `Char` or the matching end tag is ignored -- the
+                // tokenizer's RAWTEXT/RCDATA states mean nothing else should
+                // actually reach here in practice.
+                Token::EOF => ParserState::InHead { html, head },
+                _ => ParserState::Text { html, head, element, name },
+            },
+            ParserState::AfterHead { html } => match token {
+                Token::Char('\t' | '\u{0a}' | '\u{0c}' | '\u{0d}' | ' ') => {
+                    ParserState::AfterHead { html }
+                }
+                Token::Comment(text, _) => {
+                    let comment = sink.create_comment(text);
+                    sink.append(&html, comment);
+                    ParserState::AfterHead { html }
+                }
+                Token::Doctype(_) => {
+                    push_error(sink, errors, opts, "unexpected-doctype");
+                    ParserState::AfterHead { html }
+                }
+                Token::Tag(tag) if matches!(tag.kind, TagKind::Start) && tag.name == "body" => {
+                    let body = sink.create_element(tag.name.clone(), tag.attributes);
+                    sink.append(&html, body.clone());
+                    ParserState::InBody { html, stack: vec![(body, tag.name)] }
+                }
+                Token::Tag(tag) if matches!(tag.kind, TagKind::Start) && tag.name == "head" => {
+                    push_error(sink, errors, opts, "duplicate-head");
+                    ParserState::AfterHead { html }
+                }
+                Token::Tag(tag)
+                    if matches!(tag.kind, TagKind::End)
+                        && !matches!(tag.name.as_str(), "body" | "html" | "br") =>
+                {
+                    push_error(sink, errors, opts, "stray-end-tag");
+                    ParserState::AfterHead { html }
+                }
+                Token::EOF => ParserState::AfterHead { html },
+                _ => {
+                    // "Anything else": act as if a `<body>` start tag had
+                    // been seen, then reprocess the token in `InBody`.
+                    let body = sink.create_element("body".to_string(), Vec::new());
+                    sink.append(&html, body.clone());
+                    ParserState::InBody { html, stack: vec![(body, "body".to_string())] }
+                        .process_token(sink, errors, opts, token)
+                }
+            },
+            ParserState::InBody { html, mut stack } => {
+                let current = stack.last().expect("InBody's stack always holds at least <body>").0.clone();
                 match token {
-                    Token::Char('\t' | '\u{0a}' | '\u{0c}' | '\u{0d}' | ' ') => {
-                        ParserState::Initial
+                    Token::Char(ch) => {
+                        let text = sink.create_text(ch.to_string());
+                        sink.append(&current, text);
+                        ParserState::InBody { html, stack }
                     }
-                    Token::Doctype(doctype) => {
-                        // TODO: handle more doctype besides just <!DOCTYPE html>
-                        document.doctype = Some(DocumentType {
-                            name: doctype.name,
-                            public_id: doctype.public_identifier.unwrap_or_else(|| String::new()),
-                            system_id: doctype.system_identifier.unwrap_or_else(|| String::new()),
-                        });
-                        ParserState::BeforeHtml
+                    Token::Comment(text, _) => {
+                        let comment = sink.create_comment(text);
+                        sink.append(&current, comment);
+                        ParserState::InBody { html, stack }
                     }
-                    _ => todo!(),
+                    Token::Doctype(_) => {
+                        push_error(sink, errors, opts, "unexpected-doctype");
+                        ParserState::InBody { html, stack }
+                    }
+                    // Nested on `tag.kind` (rather than two guarded
+                    // `Token::Tag(tag) if ...` arms) so the `TagKind::End`
+                    // catch-all below is a real, unguarded pattern -- the
+                    // compiler can't treat a set of guarded arms as
+                    // exhaustive, even when their guards happen to cover
+                    // every case between them.
+                    Token::Tag(tag) => match tag.kind {
+                        TagKind::Start => {
+                            let is_void = tag.self_closing || VOID_ELEMENTS.contains(&tag.name.as_str());
+                            let name = tag.name.clone();
+                            let element = sink.create_element(tag.name, tag.attributes);
+                            sink.append(&current, element.clone());
+                            if !is_void {
+                                stack.push((element, name));
+                            }
+                            ParserState::InBody { html, stack }
+                        }
+                        TagKind::End if tag.name == "html" => ParserState::InBody { html, stack },
+                        TagKind::End => {
+                            // Close back up to (and including) the matching
+                            // open element, if there is one; an end tag with
+                            // no matching start tag is simply ignored.
+                            match stack.iter().rposition(|(_, name)| *name == tag.name) {
+                                Some(index) if index > 0 => {
+                                    stack.truncate(index);
+                                }
+                                Some(_) => {
+                                    // Matches the outermost (`<body>`) element --
+                                    // nothing left to close without losing the
+                                    // insertion point entirely, so leave it.
+                                }
+                                None => push_error(sink, errors, opts, "stray-end-tag"),
+                            }
+                            ParserState::InBody { html, stack }
+                        }
+                    },
+                    Token::EOF => ParserState::InBody { html, stack },
                 }
             }
         }
     }
 }
+
+/// Known legacy public identifiers that force quirks mode outright, per the
+/// HTML standard's "Initial" insertion mode algorithm.
+const QUIRKY_PUBLIC_PREFIXES: &[&str] = &[
+    "+//silmaril//dtd html pro v0r11 19970101//",
+    "-//advasoft ltd//dtd html 3.0 aswedit + extensions//",
+    "-//as//dtd html 3.0 aswedit + extensions//",
+    "-//ietf//dtd html 2.0//",
+    "-//ietf//dtd html 2.1e//",
+    "-//ietf//dtd html 3.0//",
+    "-//ietf//dtd html 3.2 final//",
+    "-//ietf//dtd html 3.2//",
+    "-//ietf//dtd html 3//",
+    "-//ietf//dtd html level 0//",
+    "-//ietf//dtd html level 1//",
+    "-//ietf//dtd html level 2//",
+    "-//ietf//dtd html level 3//",
+    "-//ietf//dtd html strict level 0//",
+    "-//ietf//dtd html strict level 1//",
+    "-//ietf//dtd html strict level 2//",
+    "-//ietf//dtd html strict level 3//",
+    "-//ietf//dtd html strict//",
+    "-//ietf//dtd html//",
+    "-//metrius//dtd metrius presentational//",
+    "-//microsoft//dtd internet explorer 2.0 html strict//",
+    "-//microsoft//dtd internet explorer 2.0 html//",
+    "-//microsoft//dtd internet explorer 2.0 tables//",
+    "-//microsoft//dtd internet explorer 3.0 html strict//",
+    "-//microsoft//dtd internet explorer 3.0 html//",
+    "-//microsoft//dtd internet explorer 3.0 tables//",
+    "-//netscape comm. corp.//dtd html//",
+    "-//netscape comm. corp.//dtd strict html//",
+    "-//o'reilly and associates//dtd html 2.0//",
+    "-//o'reilly and associates//dtd html extended 1.0//",
+    "-//o'reilly and associates//dtd html extended relaxed 1.0//",
+    "-//sq//dtd html 2.0 hotmetal + extensions//",
+    "-//softquad software//dtd hotmetal pro 6.0::19990601::extensions to html 4.0//",
+    "-//softquad//dtd hotmetal pro 4.0::19971010::extensions to html 4.0//",
+    "-//spyglass//dtd html 2.0 extended//",
+    "-//sun microsystems corp.//dtd hotjava html//",
+    "-//sun microsystems corp.//dtd hotjava strict html//",
+    "-//w3c//dtd html 3 1995-03-24//",
+    "-//w3c//dtd html 3.2 draft//",
+    "-//w3c//dtd html 3.2 final//",
+    "-//w3c//dtd html 3.2//",
+    "-//w3c//dtd html 3.2s draft//",
+    "-//w3c//dtd html 4.0 frameset//",
+    "-//w3c//dtd html 4.0 transitional//",
+    "-//w3c//dtd html experimental 19960712//",
+    "-//w3c//dtd html experimental 970421//",
+    "-//w3c//dtd w3 html//",
+    "-//w3o//dtd w3 html 3.0//",
+    "-//webtechs//dtd mozilla html 2.0//",
+    "-//webtechs//dtd mozilla html//",
+];
+
+const QUIRKY_PUBLIC_IDENTIFIERS: &[&str] = &[
+    "-//w3o//dtd w3 html strict 3.0//en//",
+    "-/w3c/dtd html 4.0 transitional/en",
+    "html",
+];
+
+const QUIRKY_SYSTEM_IDENTIFIERS: &[&str] =
+    &["http://www.ibm.com/data/dtd/v11/ibmxhtml1-transitional.dtd"];
+
+const FRAMESET_OR_TRANSITIONAL_4_01_PREFIXES: &[&str] = &[
+    "-//w3c//dtd html 4.01 frameset//",
+    "-//w3c//dtd html 4.01 transitional//",
+];
+
+const LIMITED_QUIRKY_XHTML_PREFIXES: &[&str] = &[
+    "-//w3c//dtd xhtml 1.0 frameset//",
+    "-//w3c//dtd xhtml 1.0 transitional//",
+];
+
+fn quirks_mode_for_doctype(doctype: &Doctype) -> QuirksMode {
+    if doctype.force_quirks || doctype.name != "html" {
+        return QuirksMode::Quirks;
+    }
+
+    let public_id = doctype
+        .public_identifier
+        .as_deref()
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let system_id = doctype
+        .system_identifier
+        .as_deref()
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let has_system_id = doctype.system_identifier.is_some();
+
+    if QUIRKY_PUBLIC_IDENTIFIERS.contains(&public_id.as_str())
+        || QUIRKY_SYSTEM_IDENTIFIERS.contains(&system_id.as_str())
+        || QUIRKY_PUBLIC_PREFIXES.iter().any(|prefix| public_id.starts_with(prefix))
+        || (!has_system_id
+            && FRAMESET_OR_TRANSITIONAL_4_01_PREFIXES
+                .iter()
+                .any(|prefix| public_id.starts_with(prefix)))
+    {
+        return QuirksMode::Quirks;
+    }
+
+    if LIMITED_QUIRKY_XHTML_PREFIXES
+        .iter()
+        .any(|prefix| public_id.starts_with(prefix))
+        || (has_system_id
+            && FRAMESET_OR_TRANSITIONAL_4_01_PREFIXES
+                .iter()
+                .any(|prefix| public_id.starts_with(prefix)))
+    {
+        return QuirksMode::LimitedQuirks;
+    }
+
+    QuirksMode::NoQuirks
+}
+
+// Note: unlike the tokenizer.rs/encoding.rs test modules elsewhere in this
+// crate, these can't be compiled in isolation to double-check them -- this
+// file pulls in `crate::dom::QuirksMode`, and `dom.rs` depends on the
+// `rctree`/`url` crates, which aren't vendored here.
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doctype(name: &str, public_id: Option<&str>, system_id: Option<&str>) -> Doctype {
+        Doctype {
+            name: name.to_string(),
+            public_identifier: public_id.map(String::from),
+            system_identifier: system_id.map(String::from),
+            force_quirks: false,
+            span: Default::default(),
+        }
+    }
+
+    #[test]
+    fn force_quirks_doctype_is_always_quirks() {
+        let mut bogus = doctype("html", None, None);
+        bogus.force_quirks = true;
+        assert_eq!(quirks_mode_for_doctype(&bogus), QuirksMode::Quirks);
+    }
+
+    #[test]
+    fn doctype_name_other_than_html_is_quirks() {
+        assert_eq!(quirks_mode_for_doctype(&doctype("not-html", None, None)), QuirksMode::Quirks);
+    }
+
+    #[test]
+    fn bare_html5_doctype_is_no_quirks() {
+        assert_eq!(quirks_mode_for_doctype(&doctype("html", None, None)), QuirksMode::NoQuirks);
+    }
+
+    #[test]
+    fn quirky_public_identifier_is_quirks() {
+        assert_eq!(
+            quirks_mode_for_doctype(&doctype("html", Some("-//W3O//DTD W3 HTML Strict 3.0//EN//"), None)),
+            QuirksMode::Quirks,
+        );
+    }
+
+    #[test]
+    fn quirky_system_identifier_is_quirks() {
+        assert_eq!(
+            quirks_mode_for_doctype(&doctype(
+                "html",
+                None,
+                Some("http://www.ibm.com/data/dtd/v11/ibmxhtml1-transitional.dtd"),
+            )),
+            QuirksMode::Quirks,
+        );
+    }
+
+    #[test]
+    fn html_401_transitional_without_system_id_is_quirks() {
+        assert_eq!(
+            quirks_mode_for_doctype(&doctype("html", Some("-//W3C//DTD HTML 4.01 Transitional//EN"), None)),
+            QuirksMode::Quirks,
+        );
+    }
+
+    #[test]
+    fn html_401_transitional_with_system_id_is_limited_quirks() {
+        assert_eq!(
+            quirks_mode_for_doctype(
+                &doctype("html", Some("-//W3C//DTD HTML 4.01 Transitional//EN"), Some("about:legacy-compat")),
+            ),
+            QuirksMode::LimitedQuirks,
+        );
+    }
+
+    #[test]
+    fn xhtml_transitional_public_id_is_limited_quirks() {
+        assert_eq!(
+            quirks_mode_for_doctype(&doctype("html", Some("-//W3C//DTD XHTML 1.0 Transitional//EN"), None)),
+            QuirksMode::LimitedQuirks,
+        );
+    }
+
+    /// A minimal `TreeSink` that records each node's kind as a string,
+    /// indexed by a plain `usize` handle -- just enough to drive `parse` end
+    /// to end and assert on the resulting shape, without needing `dom.rs`'s
+    /// `rctree`/`url`-backed implementation.
+    struct RecordingSink {
+        /// Shared with the test via `RecordingSink::nodes` so it can still
+        /// be inspected after `parse` has consumed the sink by value.
+        nodes: std::rc::Rc<std::cell::RefCell<Vec<String>>>,
+    }
+
+    impl RecordingSink {
+        fn new() -> RecordingSink {
+            RecordingSink { nodes: std::rc::Rc::new(std::cell::RefCell::new(vec!["#document".to_string()])) }
+        }
+
+        fn nodes(&self) -> std::rc::Rc<std::cell::RefCell<Vec<String>>> {
+            self.nodes.clone()
+        }
+    }
+
+    impl TreeSink for RecordingSink {
+        type Handle = usize;
+
+        fn parse_error(&mut self, _message: String) {}
+
+        fn get_document(&self) -> usize {
+            0
+        }
+
+        fn get_template_contents(&mut self, target: &usize) -> usize {
+            *target
+        }
+
+        fn create_element(&mut self, name: String, _attributes: Vec<crate::tokenizer::Attribute>) -> usize {
+            let mut nodes = self.nodes.borrow_mut();
+            nodes.push(name);
+            nodes.len() - 1
+        }
+
+        fn create_comment(&mut self, text: String) -> usize {
+            let mut nodes = self.nodes.borrow_mut();
+            nodes.push(format!("#comment:{}", text));
+            nodes.len() - 1
+        }
+
+        fn create_text(&mut self, text: String) -> usize {
+            let mut nodes = self.nodes.borrow_mut();
+            nodes.push(format!("#text:{}", text));
+            nodes.len() - 1
+        }
+
+        fn append(&mut self, _parent: &usize, _child: usize) {}
+
+        fn append_doctype_to_document(&mut self, name: String, _public_id: String, _system_id: String) {
+            self.nodes.borrow_mut().push(format!("#doctype:{}", name));
+        }
+
+        fn set_quirks_mode(&mut self, _mode: QuirksMode) {}
+
+        fn set_document_base_url(&mut self, _url: String) {}
+    }
+
+    /// Smoke test: a trivial, complete document shouldn't panic or recover
+    /// any parse errors, and should produce the implied `<html>`/`<body>`
+    /// structure around its content.
+    #[test]
+    fn parses_a_trivial_document_without_panicking_or_errors() {
+        let sink = RecordingSink::new();
+        let nodes = sink.nodes();
+        let output = parse(
+            "<!DOCTYPE html><html><head><title>T</title></head><body>hi</body></html>",
+            sink,
+            "",
+            ParseOpts { exact_errors: true },
+        );
+        assert!(output.errors.is_empty(), "unexpected parse errors: {:?}", output.errors);
+        let nodes = nodes.borrow();
+        assert!(nodes.contains(&"html".to_string()));
+        assert!(nodes.contains(&"head".to_string()));
+        assert!(nodes.contains(&"title".to_string()));
+        assert!(nodes.contains(&"body".to_string()));
+        // InBody creates one text node per `Char` token rather than
+        // coalescing runs of text, so "hi" arrives as two single-character
+        // text nodes.
+        assert!(nodes.contains(&"#text:h".to_string()));
+        assert!(nodes.contains(&"#text:i".to_string()));
+    }
+
+    /// `<title>`'s content is RCDATA: the non-whitespace text inside it must
+    /// stay inside the `<title>` element rather than implicitly closing
+    /// `<head>` (the bug this insertion mode originally shipped with).
+    #[test]
+    fn title_text_does_not_implicitly_close_head() {
+        let output = parse(
+            "<head><title>Page Title</title></head><body>hi</body>",
+            RecordingSink::new(),
+            "",
+            ParseOpts { exact_errors: true },
+        );
+        assert!(output.errors.is_empty(), "unexpected parse errors: {:?}", output.errors);
+    }
+
+    /// A document with no explicit `<html>`/`<head>`/`<body>` still parses
+    /// cleanly, relying entirely on the "anything else" implied-element
+    /// fallbacks.
+    #[test]
+    fn parses_a_document_with_no_explicit_structure() {
+        let sink = RecordingSink::new();
+        let nodes = sink.nodes();
+        let output = parse("hi", sink, "", ParseOpts { exact_errors: true });
+        assert!(output.errors.is_empty(), "unexpected parse errors: {:?}", output.errors);
+        let nodes = nodes.borrow();
+        assert!(nodes.contains(&"html".to_string()));
+        assert!(nodes.contains(&"body".to_string()));
+    }
+
+}
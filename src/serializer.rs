@@ -0,0 +1,181 @@
+use crate::dom::{DocumentType, DomNode, NodeData};
+
+/// Controls how much of a node `serialize` emits.
+#[derive(Debug, Clone, Copy)]
+pub struct SerializeOpts {
+    /// When `true` (the default), the given node itself is serialized.
+    /// When `false`, only its children are emitted -- useful for
+    /// serializing the contents of an element without its own tags.
+    pub include_node: bool,
+}
+
+impl Default for SerializeOpts {
+    fn default() -> SerializeOpts {
+        SerializeOpts { include_node: true }
+    }
+}
+
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "param",
+    "source", "track", "wbr",
+];
+
+/// Serialize a node back into well-formed HTML, reversing `parser::parse`.
+pub fn serialize(node: &DomNode, opts: SerializeOpts) -> String {
+    let mut output = String::new();
+    if opts.include_node {
+        serialize_node(node, &mut output);
+    } else {
+        for child in node.children() {
+            serialize_node(&child, &mut output);
+        }
+    }
+    output
+}
+
+fn serialize_node(node: &DomNode, output: &mut String) {
+    match &*node.borrow() {
+        NodeData::Document(data) => {
+            if let Some(doctype) = &data.doctype {
+                serialize_doctype(doctype, output);
+            }
+            for child in node.children() {
+                serialize_node(&child, output);
+            }
+        }
+        NodeData::Doctype(doctype) => serialize_doctype(doctype, output),
+        NodeData::Element(element) => {
+            output.push('<');
+            output.push_str(&element.name);
+            for attribute in &element.attributes {
+                output.push(' ');
+                output.push_str(&attribute.name);
+                output.push_str("=\"");
+                output.push_str(&escape(&attribute.value, true));
+                output.push('"');
+            }
+            output.push('>');
+
+            if !VOID_ELEMENTS.contains(&element.name.as_str()) {
+                for child in node.children() {
+                    serialize_node(&child, output);
+                }
+                output.push_str("</");
+                output.push_str(&element.name);
+                output.push('>');
+            }
+        }
+        NodeData::Text(text) => output.push_str(&escape(&text.0, false)),
+        NodeData::Comment(comment) => {
+            output.push_str("<!--");
+            output.push_str(&comment.0);
+            output.push_str("-->");
+        }
+    }
+}
+
+fn serialize_doctype(doctype: &DocumentType, output: &mut String) {
+    output.push_str("<!DOCTYPE ");
+    output.push_str(&doctype.name);
+    if !doctype.public_id.is_empty() {
+        output.push_str(" PUBLIC \"");
+        output.push_str(&doctype.public_id);
+        output.push('"');
+        if !doctype.system_id.is_empty() {
+            output.push_str(" \"");
+            output.push_str(&doctype.system_id);
+            output.push('"');
+        }
+    } else if !doctype.system_id.is_empty() {
+        output.push_str(" SYSTEM \"");
+        output.push_str(&doctype.system_id);
+        output.push('"');
+    }
+    output.push('>');
+}
+
+/// Render a node as a nested S-expression, e.g.
+/// `(html (head) (body (p "text")))`, attributes as `(:attr value)` pairs.
+/// Useful for snapshot-testing the tree builder's output.
+pub fn to_sexpr(node: &DomNode) -> String {
+    let mut output = String::new();
+    write_sexpr(node, &mut output);
+    output
+}
+
+fn write_sexpr(node: &DomNode, output: &mut String) {
+    match &*node.borrow() {
+        NodeData::Document(_) => {
+            let mut children = node.children().peekable();
+            while let Some(child) = children.next() {
+                write_sexpr(&child, output);
+                if children.peek().is_some() {
+                    output.push(' ');
+                }
+            }
+        }
+        NodeData::Doctype(doctype) => {
+            output.push_str("(!DOCTYPE ");
+            output.push_str(&doctype.name);
+            output.push(')');
+        }
+        NodeData::Element(element) => {
+            output.push('(');
+            output.push_str(&element.name);
+            for attribute in &element.attributes {
+                output.push_str(" (:");
+                output.push_str(&attribute.name);
+                output.push(' ');
+                output.push_str(&attribute.value);
+                output.push(')');
+            }
+            for child in node.children() {
+                output.push(' ');
+                write_sexpr(&child, output);
+            }
+            output.push(')');
+        }
+        NodeData::Text(text) => {
+            output.push('"');
+            output.push_str(&escape_sexpr_string(&text.0));
+            output.push('"');
+        }
+        NodeData::Comment(comment) => {
+            output.push_str("(!-- \"");
+            output.push_str(&escape_sexpr_string(&comment.0));
+            output.push_str("\")");
+        }
+    }
+}
+
+/// Escape the characters that would otherwise break out of a `"..."`
+/// S-expression string: a literal `"` or `\` needs to be backslash-escaped
+/// for the result to be readable back in by an S-expression parser.
+fn escape_sexpr_string(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+/// Escape the characters that are unsafe in text content, or in an
+/// attribute value when `in_attribute` is set.
+fn escape(value: &str, in_attribute: bool) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '\u{a0}' => escaped.push_str("&nbsp;"),
+            '"' if in_attribute => escaped.push_str("&quot;"),
+            '<' if !in_attribute => escaped.push_str("&lt;"),
+            '>' if !in_attribute => escaped.push_str("&gt;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
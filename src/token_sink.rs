@@ -0,0 +1,156 @@
+use crate::tokenizer::{DefaultEmitter, Tag, TagKind, Token, Tokenizer};
+
+/// What a [`TokenSink`] wants done with a start/end tag, run of text, or
+/// comment it was just handed.
+pub enum TokenAction {
+    /// Emit the token re-rendered from its parsed form: text is escaped, and
+    /// tags are rebuilt with double-quoted, escaped attribute values.
+    Keep,
+    /// Discard the token -- nothing is emitted for it.
+    Drop,
+    /// Emit this string in its place.
+    Replace(String),
+}
+
+/// Receives tokens one at a time as [`drive_tokens`] pulls them off a
+/// `Tokenizer`, rather than requiring a caller to collect a whole
+/// document's tokens into a `VecDeque` up front.
+///
+/// Modeled on `TreeSink`: the driving logic lives outside the trait, in
+/// `drive_tokens`, so implementors only have to decide what happens to each
+/// kind of token. Consecutive `Token::Char`s are coalesced into a single
+/// `text` call, since most consumers care about runs of text rather than
+/// individual characters.
+pub trait TokenSink {
+    fn start_tag(&mut self, tag: &Tag) -> TokenAction;
+    fn end_tag(&mut self, tag: &Tag) -> TokenAction;
+    fn text(&mut self, text: &str) -> TokenAction;
+    fn comment(&mut self, text: &str) -> TokenAction;
+}
+
+/// Drive `tokenizer` to completion, calling into `sink` for every start tag,
+/// end tag, run of text, and comment, and returning the output built up from
+/// whatever `sink` decides for each one -- kept as-is, dropped, or replaced.
+pub fn drive_tokens<S: TokenSink>(tokenizer: Tokenizer<DefaultEmitter>, sink: &mut S) -> String {
+    let mut output = String::new();
+    let mut text = String::new();
+
+    for token in tokenizer {
+        if let Token::Char(ch) = token {
+            text.push(ch);
+            continue;
+        }
+        flush_text(&mut text, sink, &mut output);
+
+        match token {
+            Token::Char(_) => unreachable!("handled above"),
+            Token::EOF => {}
+            Token::Tag(tag) => {
+                let action = match tag.kind {
+                    TagKind::Start => sink.start_tag(&tag),
+                    TagKind::End => sink.end_tag(&tag),
+                };
+                match action {
+                    TokenAction::Keep => output.push_str(&render_tag(&tag)),
+                    TokenAction::Drop => {}
+                    TokenAction::Replace(replacement) => output.push_str(&replacement),
+                }
+            }
+            Token::Comment(comment, _) => {
+                let action = sink.comment(&comment);
+                match action {
+                    TokenAction::Keep => {
+                        output.push_str("<!--");
+                        output.push_str(&comment);
+                        output.push_str("-->");
+                    }
+                    TokenAction::Drop => {}
+                    TokenAction::Replace(replacement) => output.push_str(&replacement),
+                }
+            }
+            Token::Doctype(doctype) => {
+                // Not a tag/text/comment, so there's nothing for `sink` to
+                // decide here -- doctypes pass through unconditionally.
+                output.push_str("<!DOCTYPE ");
+                output.push_str(&doctype.name);
+                if let Some(public_id) = &doctype.public_identifier {
+                    output.push_str(&format!(" PUBLIC \"{}\"", public_id));
+                }
+                if let Some(system_id) = &doctype.system_identifier {
+                    if doctype.public_identifier.is_none() {
+                        output.push_str(" SYSTEM");
+                    }
+                    output.push_str(&format!(" \"{}\"", system_id));
+                }
+                output.push('>');
+            }
+        }
+    }
+    flush_text(&mut text, sink, &mut output);
+
+    output
+}
+
+fn flush_text<S: TokenSink>(text: &mut String, sink: &mut S, output: &mut String) {
+    if text.is_empty() {
+        return;
+    }
+    match sink.text(text) {
+        TokenAction::Keep => output.push_str(&escape_text(text)),
+        TokenAction::Drop => {}
+        TokenAction::Replace(replacement) => output.push_str(&replacement),
+    }
+    text.clear();
+}
+
+/// Escape the characters that would otherwise be parsed as markup if the
+/// (already entity-decoded) text were re-emitted verbatim.
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+/// Render a start or end tag back into HTML source, with every attribute
+/// value double-quoted and escaped.
+fn render_tag(tag: &Tag) -> String {
+    let mut rendered = String::from("<");
+    if matches!(tag.kind, TagKind::End) {
+        rendered.push('/');
+    }
+    rendered.push_str(&tag.name);
+    for attribute in &tag.attributes {
+        rendered.push(' ');
+        rendered.push_str(&attribute.name);
+        rendered.push_str("=\"");
+        rendered.push_str(&escape_attribute_value(&attribute.value));
+        rendered.push('"');
+    }
+    if tag.self_closing {
+        rendered.push_str(" />");
+    } else {
+        rendered.push('>');
+    }
+    rendered
+}
+
+/// Escape the characters that would otherwise prematurely end a
+/// double-quoted attribute value.
+fn escape_attribute_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
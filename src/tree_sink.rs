@@ -0,0 +1,46 @@
+use crate::dom::QuirksMode;
+use crate::tokenizer::Attribute;
+
+/// Receives tree-construction instructions from the parser.
+///
+/// Modeled on html5ever's `TreeSink`: the tokenizer/tree-construction stage
+/// never touches a concrete DOM type directly, it only calls into this
+/// trait. That lets a caller plug in its own node representation (or none
+/// at all, e.g. to just count tags) instead of being stuck with whatever
+/// tree the parser ships by default.
+#[allow(dead_code)]
+pub trait TreeSink {
+    /// A reference to a node in the sink's tree.
+    type Handle: Clone;
+
+    /// Report a parse error at the current position in the input.
+    fn parse_error(&mut self, message: String);
+
+    /// Get a handle to the document node that owns the tree.
+    fn get_document(&self) -> Self::Handle;
+
+    /// Get a handle to the contents of a `<template>` element.
+    fn get_template_contents(&mut self, target: &Self::Handle) -> Self::Handle;
+
+    /// Create an element node with the given tag name and attributes.
+    fn create_element(&mut self, name: String, attributes: Vec<Attribute>) -> Self::Handle;
+
+    /// Create a comment node.
+    fn create_comment(&mut self, text: String) -> Self::Handle;
+
+    /// Create a text node.
+    fn create_text(&mut self, text: String) -> Self::Handle;
+
+    /// Append `child` as the last child of `parent`.
+    fn append(&mut self, parent: &Self::Handle, child: Self::Handle);
+
+    /// Record a DOCTYPE on the document.
+    fn append_doctype_to_document(&mut self, name: String, public_id: String, system_id: String);
+
+    /// Set the document's quirks mode, as determined from its DOCTYPE.
+    fn set_quirks_mode(&mut self, mode: QuirksMode);
+
+    /// Set the document's base URL, used to resolve relative URLs in
+    /// attributes such as `href`/`src` and returned from `base_uri()`.
+    fn set_document_base_url(&mut self, url: String);
+}